@@ -5,12 +5,13 @@ use std::{
         self,
         Write,
     },
+    sync::Arc,
+    time::Duration,
 };
 
 use convex::{
     ConvexClient,
     ConvexClientBuilder,
-    FunctionResult,
     Value,
 };
 use pyo3::{
@@ -19,13 +20,7 @@ use pyo3::{
     pyclass,
     types::PyDict,
 };
-use tokio::{
-    runtime,
-    time::{
-        sleep,
-        Duration,
-    },
-};
+use tokio::runtime;
 use tracing::{
     field::{
         Field,
@@ -44,11 +39,16 @@ use tracing_subscriber::{
 };
 
 use crate::{
+    lazy_value::LazyValue,
+    promise::RustPromise,
     query_result::{
-        convex_error_to_py_wrapped,
+        function_result_to_py,
         py_to_value,
         value_to_py,
-        value_to_py_wrapped,
+    },
+    signals::{
+        check_python_signals_periodically,
+        default_poll_interval,
     },
     subscription::{
         PyQuerySetSubscription,
@@ -56,9 +56,15 @@ use crate::{
     },
 };
 
-/// A wrapper type that can accept a Python `Dict[str, CoercibleToConvexValue]`
+/// A wrapper type that can accept a Python `Dict[str, CoercibleToConvexValue]`.
+///
+/// Conversion to `convex::Value` is deferred to [`FunctionArgsWrapper::into_values`]
+/// rather than done here in `extract`, since whether ints are coerced
+/// strictly depends on the `PyConvexClient` the args are passed to (see
+/// `PyConvexClient::strict_int_mode`), which isn't available to a bare
+/// `FromPyObject` impl.
 #[derive(Default)]
-pub struct FunctionArgsWrapper(BTreeMap<String, Value>);
+pub struct FunctionArgsWrapper(BTreeMap<String, Py<PyAny>>);
 impl<'a, 'py> FromPyObject<'a, 'py> for FunctionArgsWrapper {
     type Error = PyErr;
 
@@ -70,8 +76,7 @@ impl<'a, 'py> FromPyObject<'a, 'py> for FunctionArgsWrapper {
             .iter()
             .map(|(key, value)| {
                 let k = key.extract::<String>()?;
-                let v = py_to_value(value.as_borrowed())?;
-                Ok((k, v))
+                Ok((k, value.unbind()))
             })
             .collect::<PyResult<_>>()?;
 
@@ -79,48 +84,61 @@ impl<'a, 'py> FromPyObject<'a, 'py> for FunctionArgsWrapper {
     }
 }
 
-async fn check_python_signals_periodically() -> PyErr {
-    loop {
-        sleep(Duration::from_secs(1)).await;
-        if let Err(e) = Python::attach(|py| py.check_signals()) {
-            return e;
-        }
+impl FunctionArgsWrapper {
+    fn into_values(self, py: Python<'_>, strict_int_mode: bool) -> PyResult<BTreeMap<String, Value>> {
+        self.0
+            .into_iter()
+            .map(|(k, v)| Ok((k, py_to_value(v.bind(py).as_borrowed(), strict_int_mode)?)))
+            .collect()
     }
 }
+
 /// An asynchronous client to interact with a specific project to perform
 /// queries/mutations/actions and manage query subscriptions.
+///
+/// `rt` is shared behind an `Arc` so that [`PyConvexClient::clone`] can hand
+/// out another handle to the same runtime and WebSocket (via
+/// `ConvexClient::clone`) instead of spinning up a second one. It's `None`
+/// once [`PyConvexClient::close`] has been called on this handle.
 #[pyclass]
 pub struct PyConvexClient {
-    rt: tokio::runtime::Runtime,
+    rt: Option<Arc<tokio::runtime::Runtime>>,
     client: ConvexClient,
+    /// Whether this client's calls coerce every Python `int` to
+    /// `Value::Int64`, even in-range ones that would fit exactly in an
+    /// `f64`. Set once, from the constructor, rather than through ambient
+    /// global state, so two clients in the same interpreter (or the same
+    /// client used from multiple threads) can't stomp on each other's
+    /// int-coercion behavior.
+    strict_int_mode: bool,
+    /// How often blocking calls on this client poll for a pending Python
+    /// signal (e.g. a `KeyboardInterrupt`). Set once, from the constructor,
+    /// falling back to [`default_poll_interval`] (itself overridable
+    /// process-wide via `CONVEX_SIGNAL_POLL_INTERVAL_MS`) so two clients in
+    /// the same interpreter can use different responsiveness without
+    /// mutating shared env state.
+    poll_interval: Duration,
 }
 
-impl PyConvexClient {
-    fn function_result_to_py_result(
-        &mut self,
-        py: Python<'_>,
-        result: FunctionResult,
-    ) -> PyResult<Py<PyAny>> {
-        match result {
-            FunctionResult::Value(v) => Ok(value_to_py_wrapped(py, v)),
-            FunctionResult::ErrorMessage(e) => Err(PyException::new_err(e)),
-            FunctionResult::ConvexError(v) => {
-                // pyo3 can't defined new custom exceptions when using the common abi
-                // `features = ["abi3"]` https://github.com/PyO3/pyo3/issues/1344
-                // so we define this error in Python. So just return a wrapped one.
-                Ok(convex_error_to_py_wrapped(py, v))
-            },
-        }
-    }
+/// The error raised by any `PyConvexClient` method once the handle has been
+/// closed.
+fn closed_err() -> PyErr {
+    PyException::new_err("PyConvexClient is closed")
+}
 
+impl PyConvexClient {
     fn block_on_and_check_signals<'a, T, E: ToString, F: Future<Output = Result<T, E>>>(
         &'a mut self,
         f: impl FnOnce(&'a mut ConvexClient) -> F,
     ) -> PyResult<T> {
-        self.rt.block_on(async {
+        let Some(rt) = self.rt.as_deref() else {
+            return Err(closed_err());
+        };
+        let poll_interval = self.poll_interval;
+        rt.block_on(async {
             tokio::select!(
                 res1 = f(&mut self.client) => res1.map_err(|e| PyException::new_err(e.to_string())),
-                res2 = check_python_signals_periodically() => Err(res2),
+                res2 = check_python_signals_periodically(poll_interval) => Err(res2),
             )
         })
     }
@@ -130,17 +148,35 @@ impl PyConvexClient {
 impl PyConvexClient {
     /// Note that the WebSocket is not connected yet and therefore the
     /// connection url is not validated to be accepting connections.
+    ///
+    /// If `strict_int_mode` is set, every Python `int` passed as an argument
+    /// to a query/mutation/action through this client is coerced to
+    /// `Value::Int64`, even ones that would fit exactly in an `f64`.
+    ///
+    /// `poll_interval_ms`, if given, overrides how often this client's
+    /// blocking calls poll for a pending Python signal (e.g. a
+    /// `KeyboardInterrupt`); otherwise it falls back to
+    /// `signals::default_poll_interval` (50ms, or
+    /// `CONVEX_SIGNAL_POLL_INTERVAL_MS` if set).
     #[new]
-    fn py_new(deployment_url: &str, version: &str) -> PyResult<Self> {
+    #[pyo3(signature = (deployment_url, version, strict_int_mode=false, poll_interval_ms=None))]
+    fn py_new(
+        deployment_url: &str,
+        version: &str,
+        strict_int_mode: bool,
+        poll_interval_ms: Option<u64>,
+    ) -> PyResult<Self> {
         // The ConvexClient is instantiated in the context of a tokio Runtime, and
         // needs to run its worker in the background so that it can constantly
         // listen for new messages from the server. Here, we choose to build a
         // multi-thread scheduler to make that possible.
-        let rt = runtime::Builder::new_multi_thread()
-            .enable_all()
-            .worker_threads(1)
-            .build()
-            .unwrap();
+        let rt = Arc::new(
+            runtime::Builder::new_multi_thread()
+                .enable_all()
+                .worker_threads(1)
+                .build()
+                .unwrap(),
+        );
 
         // Block on the async function using the Tokio runtime.
         let client_id = format!("python-{version}");
@@ -151,8 +187,12 @@ impl PyConvexClient {
         );
         match instance {
             Ok(instance) => Ok(PyConvexClient {
-                rt,
+                rt: Some(rt),
                 client: instance,
+                strict_int_mode,
+                poll_interval: poll_interval_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or_else(default_poll_interval),
             }),
             Err(e) => Err(PyException::new_err(format!(
                 "{}: {}",
@@ -166,17 +206,24 @@ impl PyConvexClient {
     #[pyo3(signature = (name, args=None))]
     pub fn subscribe(
         &mut self,
+        py: Python<'_>,
         name: &str,
         args: Option<FunctionArgsWrapper>,
     ) -> PyResult<PyQuerySubscription> {
-        let args: BTreeMap<String, Value> = args.unwrap_or_default().0;
+        let args = args.unwrap_or_default().into_values(py, self.strict_int_mode)?;
         let res = self.block_on_and_check_signals(|client| client.subscribe(name, args))?;
-        Ok(PyQuerySubscription::new(res, self.rt.handle().clone()))
+        let Some(rt) = self.rt.as_ref() else {
+            return Err(closed_err());
+        };
+        Ok(PyQuerySubscription::new(res, rt.clone(), self.poll_interval))
     }
 
     /// Make a oneshot request to a query `name` with `args`.
     ///
-    /// Returns a `convex::Value` representing the result of the query.
+    /// Returns whatever the query returned, decoded to a Python value. If the
+    /// query failed, raises `_convex.errors.ConvexError` (the function threw
+    /// a structured `ConvexError`) or `_convex.errors.ConvexServerError` (any
+    /// other failure, e.g. an unhandled exception) instead of returning.
     #[pyo3(signature = (name, args=None))]
     pub fn query(
         &mut self,
@@ -184,13 +231,41 @@ impl PyConvexClient {
         name: &str,
         args: Option<FunctionArgsWrapper>,
     ) -> PyResult<Py<PyAny>> {
-        let args: BTreeMap<String, Value> = args.unwrap_or_default().0;
+        let args = args.unwrap_or_default().into_values(py, self.strict_int_mode)?;
         let res = self.block_on_and_check_signals(|client| client.query(name, args))?;
-        self.function_result_to_py_result(py, res)
+        function_result_to_py(py, res)
     }
 
-    /// Perform a mutation `name` with `args` and return a future
-    /// containing the return value of the mutation once it completes.
+    /// Like [`query`](Self::query), but returns a `RustPromise` immediately
+    /// instead of blocking the calling thread, so it can be `await`ed from an
+    /// asyncio event loop.
+    #[pyo3(signature = (name, args=None))]
+    pub fn query_async(
+        &mut self,
+        py: Python<'_>,
+        name: &str,
+        args: Option<FunctionArgsWrapper>,
+    ) -> PyResult<RustPromise> {
+        let name = name.to_string();
+        let args = args.unwrap_or_default().into_values(py, self.strict_int_mode)?;
+        let Some(rt) = self.rt.as_ref() else {
+            return Err(closed_err());
+        };
+        let mut client = self.client.clone();
+        let handle = rt.spawn(async move {
+            let res = client
+                .query(&name, args)
+                .await
+                .map_err(|e| PyException::new_err(e.to_string()))?;
+            Python::attach(|py| function_result_to_py(py, res))
+        });
+        Ok(RustPromise::new(handle, rt.clone(), self.poll_interval))
+    }
+
+    /// Perform a mutation `name` with `args` and return the mutation's
+    /// return value, decoded to a Python value. If the mutation failed,
+    /// raises `_convex.errors.ConvexError` or `_convex.errors.ConvexServerError`
+    /// instead of returning -- see [`query`](Self::query).
     #[pyo3(signature = (name, args=None))]
     pub fn mutation(
         &mut self,
@@ -198,13 +273,41 @@ impl PyConvexClient {
         name: &str,
         args: Option<FunctionArgsWrapper>,
     ) -> PyResult<Py<PyAny>> {
-        let args: BTreeMap<String, Value> = args.unwrap_or_default().0;
+        let args = args.unwrap_or_default().into_values(py, self.strict_int_mode)?;
         let res = self.block_on_and_check_signals(|client| client.mutation(name, args))?;
-        self.function_result_to_py_result(py, res)
+        function_result_to_py(py, res)
     }
 
-    /// Perform an action `name` with `args` and return a future
-    /// containing the return value of the action once it completes.
+    /// Like [`mutation`](Self::mutation), but returns a `RustPromise`
+    /// immediately instead of blocking the calling thread, so it can be
+    /// `await`ed from an asyncio event loop.
+    #[pyo3(signature = (name, args=None))]
+    pub fn mutation_async(
+        &mut self,
+        py: Python<'_>,
+        name: &str,
+        args: Option<FunctionArgsWrapper>,
+    ) -> PyResult<RustPromise> {
+        let name = name.to_string();
+        let args = args.unwrap_or_default().into_values(py, self.strict_int_mode)?;
+        let Some(rt) = self.rt.as_ref() else {
+            return Err(closed_err());
+        };
+        let mut client = self.client.clone();
+        let handle = rt.spawn(async move {
+            let res = client
+                .mutation(&name, args)
+                .await
+                .map_err(|e| PyException::new_err(e.to_string()))?;
+            Python::attach(|py| function_result_to_py(py, res))
+        });
+        Ok(RustPromise::new(handle, rt.clone(), self.poll_interval))
+    }
+
+    /// Perform an action `name` with `args` and return the action's return
+    /// value, decoded to a Python value. If the action failed, raises
+    /// `_convex.errors.ConvexError` or `_convex.errors.ConvexServerError`
+    /// instead of returning -- see [`query`](Self::query).
     #[pyo3(signature = (name, args=None))]
     pub fn action(
         &mut self,
@@ -212,18 +315,55 @@ impl PyConvexClient {
         name: &str,
         args: Option<FunctionArgsWrapper>,
     ) -> PyResult<Py<PyAny>> {
-        let args: BTreeMap<String, Value> = args.unwrap_or_default().0;
+        let args = args.unwrap_or_default().into_values(py, self.strict_int_mode)?;
         let res = self.block_on_and_check_signals(|client| client.action(name, args))?;
-        self.function_result_to_py_result(py, res)
+        function_result_to_py(py, res)
+    }
+
+    /// Like [`action`](Self::action), but returns a `RustPromise` immediately
+    /// instead of blocking the calling thread, so it can be `await`ed from an
+    /// asyncio event loop.
+    #[pyo3(signature = (name, args=None))]
+    pub fn action_async(
+        &mut self,
+        py: Python<'_>,
+        name: &str,
+        args: Option<FunctionArgsWrapper>,
+    ) -> PyResult<RustPromise> {
+        let name = name.to_string();
+        let args = args.unwrap_or_default().into_values(py, self.strict_int_mode)?;
+        let Some(rt) = self.rt.as_ref() else {
+            return Err(closed_err());
+        };
+        let mut client = self.client.clone();
+        let handle = rt.spawn(async move {
+            let res = client
+                .action(&name, args)
+                .await
+                .map_err(|e| PyException::new_err(e.to_string()))?;
+            Python::attach(|py| function_result_to_py(py, res))
+        });
+        Ok(RustPromise::new(handle, rt.clone(), self.poll_interval))
     }
 
     /// Get a consistent view of the results of every query the client is
     /// currently subscribed to. This set changes over time as subscriptions
     /// are added and dropped.
-    pub fn watch_all(&mut self, _py: Python<'_>) -> PyQuerySetSubscription {
+    ///
+    /// If `lazy` is set, each update's values are returned as `LazyValue`
+    /// views that only decode the fields actually accessed, rather than
+    /// eagerly converting the whole result set to Python objects. Useful
+    /// when subscribed to wide query sets but only reading a few fields.
+    #[pyo3(signature = (lazy=false))]
+    pub fn watch_all(&mut self, _py: Python<'_>, lazy: bool) -> PyResult<PyQuerySetSubscription> {
+        let Some(rt) = self.rt.as_ref() else {
+            return Err(closed_err());
+        };
         let mut py_res: PyQuerySetSubscription = self.client.watch_all().into();
-        py_res.rt_handle = Some(self.rt.handle().clone());
-        py_res
+        py_res.rt = Some(rt.clone());
+        py_res.poll_interval = self.poll_interval;
+        py_res.lazy = lazy;
+        Ok(py_res)
     }
 
     /// Set auth for use when calling Convex functions.
@@ -233,10 +373,14 @@ impl PyConvexClient {
     /// out).
     #[pyo3(signature = (token=None))]
     pub fn set_auth(&mut self, token: Option<String>) -> PyResult<()> {
-        self.rt.block_on(async {
+        let Some(rt) = self.rt.as_deref() else {
+            return Err(closed_err());
+        };
+        let poll_interval = self.poll_interval;
+        rt.block_on(async {
             tokio::select!(
                 () = self.client.set_auth(token) => Ok(()),
-                err = check_python_signals_periodically() => Err(err),
+                err = check_python_signals_periodically(poll_interval) => Err(err),
             )
         })
     }
@@ -246,13 +390,68 @@ impl PyConvexClient {
     /// Set it with a deploy key obtained from the convex dashboard of a
     /// deployment you control. This auth cannot be unset.
     pub fn set_admin_auth(&mut self, token: String) -> PyResult<()> {
-        self.rt.block_on(async {
+        let Some(rt) = self.rt.as_deref() else {
+            return Err(closed_err());
+        };
+        let poll_interval = self.poll_interval;
+        rt.block_on(async {
             tokio::select!(
                 () = self.client.set_admin_auth(token, None) => Ok(()),
-                err = check_python_signals_periodically() => Err(err),
+                err = check_python_signals_periodically(poll_interval) => Err(err),
             )
         })
     }
+
+    /// Create another handle to the same underlying connection. The clone
+    /// shares this client's WebSocket and runtime (via `ConvexClient::clone`)
+    /// rather than opening a second connection, so subscriptions made through
+    /// either handle are deduplicated server-side.
+    fn clone(&self) -> PyResult<PyConvexClient> {
+        let Some(rt) = self.rt.as_ref() else {
+            return Err(closed_err());
+        };
+        Ok(PyConvexClient {
+            rt: Some(rt.clone()),
+            client: self.client.clone(),
+            strict_int_mode: self.strict_int_mode,
+            poll_interval: self.poll_interval,
+        })
+    }
+
+    fn __copy__(&self) -> PyResult<PyConvexClient> {
+        self.clone()
+    }
+
+    /// Release this handle's WebSocket connection. After this, any further
+    /// call on this handle raises a `PyException` instead of hanging on a
+    /// dead runtime. The runtime itself is only shut down once every other
+    /// holder of it -- other `PyConvexClient` handles from `clone()`, as
+    /// well as any `PyQuerySubscription`/`PyQuerySetSubscription`/
+    /// `RustPromise` derived from it -- has let go of its own `Arc`, so
+    /// outstanding subscriptions and promises keep working until they're
+    /// closed or dropped too.
+    fn close(&mut self) {
+        if let Some(rt) = self.rt.take() {
+            if let Ok(rt) = Arc::try_unwrap(rt) {
+                rt.shutdown_background();
+            }
+        }
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    #[pyo3(signature = (_exc_type, _exc_value, _traceback))]
+    fn __exit__(
+        &mut self,
+        _exc_type: Py<PyAny>,
+        _exc_value: Py<PyAny>,
+        _traceback: Py<PyAny>,
+    ) -> bool {
+        self.close();
+        false
+    }
 }
 
 struct UDFLogVisitor {
@@ -275,23 +474,55 @@ impl Visit for UDFLogVisitor {
     }
 }
 
-struct ConvexLoggingLayer;
+/// Forwards UDF log events either to a user-supplied Python callback (full
+/// structured fields, the log level, and the event target) or, if none was
+/// given, to stdout as a plain message (the original behavior).
+struct ConvexLoggingLayer {
+    callback: Option<Py<PyAny>>,
+}
 
 impl<S: Subscriber> Layer<S> for ConvexLoggingLayer {
     fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
         let mut visitor = UDFLogVisitor::new();
         event.record(&mut visitor);
-        let mut log_writer = io::stdout();
-        if let Some(message) = visitor.fields.get("message") {
-            writeln!(log_writer, "{message}").unwrap();
+        let Some(callback) = &self.callback else {
+            let mut log_writer = io::stdout();
+            if let Some(message) = visitor.fields.get("message") {
+                writeln!(log_writer, "{message}").unwrap();
+            }
+            return;
+        };
+        let metadata = event.metadata();
+        let result = Python::attach(|py| -> PyResult<()> {
+            let py_dict = PyDict::new(py);
+            for (key, value) in &visitor.fields {
+                py_dict.set_item(key, value)?;
+            }
+            py_dict.set_item("target", metadata.target())?;
+            callback.call1(py, (py_dict, metadata.level().as_str()))?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            Python::attach(|py| e.print(py));
         }
     }
 }
 
+/// Set up a tracing subscriber that forwards UDF logs from Convex functions.
+///
+/// If `callback` is given, it's invoked for every log event as
+/// `callback(fields: dict, level: str)`, where `fields` is the event's full
+/// structured field map (plus a `"target"` entry) -- this lets Python route
+/// logs through the stdlib `logging` module or ship them elsewhere. If
+/// `callback` is `None`, log messages are written to stdout as before. `debug`
+/// controls whether `DEBUG`-level UDF logs are forwarded at all, in addition
+/// to `INFO` and above.
 #[pyfunction]
-fn init_logging() {
-    let subscriber = Registry::default().with(ConvexLoggingLayer.with_filter(
-        tracing_subscriber::filter::Targets::new().with_target("convex_logs", Level::DEBUG),
+#[pyo3(signature = (callback=None, debug=false))]
+fn init_logging(callback: Option<Py<PyAny>>, debug: bool) {
+    let level = if debug { Level::DEBUG } else { Level::INFO };
+    let subscriber = Registry::default().with(ConvexLoggingLayer { callback }.with_filter(
+        tracing_subscriber::filter::Targets::new().with_target("convex_logs", level),
     ));
 
     set_global_default(subscriber).expect("Failed to set up custom logging subscriber");
@@ -299,10 +530,15 @@ fn init_logging() {
 
 // Exposed for testing
 #[pyfunction]
-fn py_to_rust_to_py(py: Python<'_>, py_val: Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+#[pyo3(signature = (py_val, strict_int_mode=false))]
+fn py_to_rust_to_py(
+    py: Python<'_>,
+    py_val: Bound<'_, PyAny>,
+    strict_int_mode: bool,
+) -> PyResult<Py<PyAny>> {
     // this is just a map
-    match py_to_value(py_val.as_borrowed()) {
-        Ok(val) => Ok(value_to_py(py, val)),
+    match py_to_value(py_val.as_borrowed(), strict_int_mode) {
+        Ok(val) => Ok(value_to_py(py, val)?),
         Err(err) => Err(err),
     }
 }
@@ -313,6 +549,8 @@ fn _convex(_py: Python, m: Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyConvexClient>()?;
     m.add_class::<PyQuerySubscription>()?;
     m.add_class::<PyQuerySetSubscription>()?;
+    m.add_class::<LazyValue>()?;
+    m.add_class::<RustPromise>()?;
     m.add_function(wrap_pyfunction!(init_logging, &m)?)?;
     m.add_function(wrap_pyfunction!(py_to_rust_to_py, &m)?)?;
     Ok(())
@@ -0,0 +1,164 @@
+//! An opt-in lazily-decoded view over a `convex::Value`.
+//!
+//! `QuerySetSubscription.next()`/`anext()` normally deep-convert every value
+//! in the result set into Python objects on every update, which is wasteful
+//! when a caller only reads one subscription's top-level fields. When a
+//! subscription is created in "lazy" mode, container values (arrays and
+//! objects) are wrapped in a [`LazyValue`] instead: each field or item is
+//! only converted to a Python object the first time it's accessed, and the
+//! result is memoized so repeat access doesn't redo the work.
+
+use std::collections::BTreeMap;
+
+use parking_lot::Mutex;
+use pyo3::{
+    exceptions::{
+        PyIndexError,
+        PyKeyError,
+        PyStopIteration,
+        PyTypeError,
+    },
+    prelude::*,
+    types::PyList,
+};
+
+use crate::query_result::value_to_py;
+
+#[derive(Default)]
+struct Memo {
+    object_fields: BTreeMap<String, PyObject>,
+    array_items: BTreeMap<usize, PyObject>,
+}
+
+/// A lazily-decoded view over a `convex::Value::Array` or `::Object`. See
+/// the module docs for why this exists.
+#[pyclass]
+pub struct LazyValue {
+    value: convex::Value,
+    memo: Mutex<Memo>,
+}
+
+impl LazyValue {
+    fn new(value: convex::Value) -> Self {
+        LazyValue {
+            value,
+            memo: Mutex::new(Memo::default()),
+        }
+    }
+
+    /// Wrap `value` in a `LazyValue` if it's a container worth deferring the
+    /// decode of; scalars are decoded eagerly since there's nothing to defer.
+    pub fn wrap(py: Python<'_>, value: convex::Value) -> PyResult<PyObject> {
+        match value {
+            convex::Value::Array(_) | convex::Value::Object(_) => {
+                Ok(Py::new(py, LazyValue::new(value))?.into_any().unbind())
+            },
+            scalar => value_to_py(py, scalar),
+        }
+    }
+}
+
+#[pymethods]
+impl LazyValue {
+    fn __len__(&self) -> PyResult<usize> {
+        match &self.value {
+            convex::Value::Array(arr) => Ok(arr.len()),
+            convex::Value::Object(obj) => Ok(obj.len()),
+            _ => Err(PyTypeError::new_err("LazyValue scalar has no length")),
+        }
+    }
+
+    fn __getitem__(&self, py: Python<'_>, key: Bound<'_, PyAny>) -> PyResult<PyObject> {
+        match &self.value {
+            convex::Value::Array(arr) => {
+                let index: usize = key.extract()?;
+                if let Some(cached) = self.memo.lock().array_items.get(&index) {
+                    return Ok(cached.clone_ref(py));
+                }
+                let item = arr
+                    .get(index)
+                    .ok_or_else(|| PyIndexError::new_err("LazyValue array index out of range"))?
+                    .clone();
+                let py_item = LazyValue::wrap(py, item)?;
+                self.memo
+                    .lock()
+                    .array_items
+                    .insert(index, py_item.clone_ref(py));
+                Ok(py_item)
+            },
+            convex::Value::Object(obj) => {
+                let field: String = key.extract()?;
+                if let Some(cached) = self.memo.lock().object_fields.get(&field) {
+                    return Ok(cached.clone_ref(py));
+                }
+                let item = obj
+                    .get(&field)
+                    .ok_or_else(|| PyKeyError::new_err(field.clone()))?
+                    .clone();
+                let py_item = LazyValue::wrap(py, item)?;
+                self.memo
+                    .lock()
+                    .object_fields
+                    .insert(field, py_item.clone_ref(py));
+                Ok(py_item)
+            },
+            _ => Err(PyTypeError::new_err("LazyValue scalar is not subscriptable")),
+        }
+    }
+
+    fn __iter__(slf: Py<Self>, py: Python<'_>) -> PyResult<PyObject> {
+        // Mirrors `dict.__iter__`: an object yields its (already-cheap) keys
+        // rather than decoded values. Figure out which shape we have before
+        // possibly moving `slf` into the array iterator below.
+        enum Shape {
+            ObjectKeys(Vec<String>),
+            Array,
+            Scalar,
+        }
+        let shape = match &slf.borrow(py).value {
+            convex::Value::Object(obj) => Shape::ObjectKeys(obj.keys().cloned().collect()),
+            convex::Value::Array(_) => Shape::Array,
+            _ => Shape::Scalar,
+        };
+        match shape {
+            Shape::ObjectKeys(keys) => {
+                let keys = PyList::new(py, keys)?;
+                Ok(keys.try_iter()?.unbind().into_any())
+            },
+            Shape::Array => {
+                let iter = LazyValueArrayIter { inner: slf, index: 0 };
+                Ok(Py::new(py, iter)?.into_any().unbind())
+            },
+            Shape::Scalar => Err(PyTypeError::new_err("LazyValue scalar is not iterable")),
+        }
+    }
+}
+
+/// Yields each array item of a [`LazyValue`] one at a time, decoding (and
+/// memoizing) it lazily via `LazyValue.__getitem__` rather than materializing
+/// the whole array up front.
+#[pyclass]
+struct LazyValueArrayIter {
+    inner: Py<LazyValue>,
+    index: usize,
+}
+
+#[pymethods]
+impl LazyValueArrayIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let len = self.inner.borrow(py).__len__()?;
+        if self.index >= len {
+            return Err(PyStopIteration::new_err("LazyValue array exhausted"));
+        }
+        let item = self
+            .inner
+            .borrow(py)
+            .__getitem__(py, self.index.into_pyobject(py)?.into_any())?;
+        self.index += 1;
+        Ok(item)
+    }
+}
@@ -8,6 +8,12 @@
 //! There is a Python layer above this package which re-exposes some of these
 //! pyo3 structs in a more Pythonic way. Please refer to https://pypi.org/project/convex/
 //! for official Python client documentation.
+//!
+//! Note: `query`/`mutation`/`action`/`PyQuerySubscription.next` now raise
+//! `_convex.errors.ConvexError`/`ConvexServerError` on failure instead of
+//! returning the old `{"type": "value" | "convexerror" | "error", ...}` dict.
+//! That higher-level package lives outside this crate and needs a matching
+//! update wherever it still unwraps the old shape.
 
 #![warn(missing_docs)]
 #![warn(rustdoc::missing_crate_level_docs)]
@@ -15,5 +21,8 @@
 mod client;
 pub use client::PyConvexClient;
 
+mod lazy_value;
+mod promise;
 mod query_result;
+mod signals;
 mod subscription;
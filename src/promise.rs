@@ -0,0 +1,87 @@
+//! A handle to an in-flight query/mutation/action spawned onto the tokio
+//! runtime by `PyConvexClient::query_async`/`mutation_async`/`action_async`.
+//!
+//! It can be consumed either synchronously, by blocking on
+//! [`RustPromise::result`], or asynchronously via `await`: `__await__` hands
+//! the `JoinHandle` to `pyo3_async_runtimes::tokio::future_into_py` (the same
+//! bridge `PyQuerySubscription`/`PyQuerySetSubscription` use for `anext`) and
+//! returns its iterator, rather than polling `is_finished()` in a loop.
+
+use std::{
+    sync::Arc,
+    time::Duration,
+};
+
+use pyo3::{
+    exceptions::PyException,
+    prelude::*,
+};
+use tokio::{
+    runtime::Runtime,
+    task::JoinHandle,
+};
+
+use crate::signals::check_python_signals_periodically;
+
+/// An awaitable handle to a query/mutation/action running in the background.
+#[pyclass]
+pub struct RustPromise {
+    handle: Option<JoinHandle<PyResult<PyObject>>>,
+    rt: Arc<Runtime>,
+    poll_interval: Duration,
+}
+
+impl RustPromise {
+    /// Wrap a spawned `JoinHandle` so it can be handed back to Python as an
+    /// awaitable. Holding onto `rt` keeps the runtime this handle depends on
+    /// alive even if the `PyConvexClient` it came from is closed first.
+    /// `poll_interval` is the signal-check interval of the `PyConvexClient`
+    /// that spawned `handle`.
+    pub fn new(handle: JoinHandle<PyResult<PyObject>>, rt: Arc<Runtime>, poll_interval: Duration) -> Self {
+        RustPromise {
+            handle: Some(handle),
+            rt,
+            poll_interval,
+        }
+    }
+}
+
+fn already_consumed_err() -> PyErr {
+    PyException::new_err("RustPromise was already awaited")
+}
+
+#[pymethods]
+impl RustPromise {
+    /// Block the calling thread until the result is ready, still responsive
+    /// to Ctrl-C via `check_python_signals_periodically`.
+    fn result(&mut self) -> PyResult<PyObject> {
+        let Some(handle) = self.handle.take() else {
+            return Err(already_consumed_err());
+        };
+        self.rt.block_on(async {
+            tokio::select!(
+                res = handle => match res {
+                    Ok(inner) => inner,
+                    Err(e) => Err(PyException::new_err(e.to_string())),
+                },
+                err = check_python_signals_periodically(self.poll_interval) => Err(err),
+            )
+        })
+    }
+
+    /// Returns the iterator asyncio drives to await this promise. Delegates
+    /// to `future_into_py`'s own `__await__` instead of hand-rolling a
+    /// poll-and-reschedule loop.
+    fn __await__(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let Some(handle) = self.handle.take() else {
+            return Err(already_consumed_err());
+        };
+        let coro = pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            match handle.await {
+                Ok(inner) => inner,
+                Err(e) => Err(PyException::new_err(e.to_string())),
+            }
+        })?;
+        Ok(coro.call_method0("__await__")?.unbind())
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::BTreeMap;
 
-use convex::ConvexError;
+use convex::{
+    ConvexError,
+    FunctionResult,
+};
 use pyo3::{
     exceptions::PyException,
     types::{
@@ -10,53 +13,85 @@ use pyo3::{
         PyDict,
         PyDictMethods,
         PyFloat,
+        PyFrozenSet,
         PyInt,
         PyList,
         PyListMethods,
+        PySet,
         PyString,
+        PyTuple,
     },
     Borrowed,
+    Bound,
     PyAny,
     PyObject,
     PyResult,
     Python,
 };
 
-// TODO using an enum would be cleaner here
-pub fn value_to_py_wrapped(py: Python<'_>, v: convex::Value) -> PyObject {
-    let py_dict = PyDict::new(py);
-    py_dict
-        .set_item("type", PyString::new(py, "value"))
-        .unwrap();
-    py_dict.set_item("value", value_to_py(py, v)).unwrap();
-    py_dict.into()
+/// The bound (2^53) of the range `-2^53..=2^53` within which every integer
+/// can be represented exactly as an `f64`.
+const MAX_SAFE_INTEGER: i64 = 1 << 53;
+
+/// Build a Python `_convex.errors.ConvexError` for an application error that
+/// was thrown intentionally (a Convex function called `ConvexError`).
+pub fn build_convex_error<'py>(
+    py: Python<'py>,
+    err: ConvexError,
+) -> PyResult<Bound<'py, PyAny>> {
+    let errors_module = py.import("_convex.errors")?;
+    let convex_error_class = errors_module.getattr("ConvexError")?;
+    let data = value_to_py(py, err.data)?;
+    convex_error_class.call1((err.message, data))
+}
+
+/// Build a Python `_convex.errors.ConvexServerError` for an unstructured
+/// error message (e.g. an unhandled exception in a Convex function).
+pub fn build_server_error<'py>(py: Python<'py>, message: String) -> PyResult<Bound<'py, PyAny>> {
+    let errors_module = py.import("_convex.errors")?;
+    let server_error_class = errors_module.getattr("ConvexServerError")?;
+    server_error_class.call1((message,))
 }
 
-pub fn convex_error_to_py_wrapped(py: Python<'_>, err: ConvexError) -> PyObject {
-    let py_dict = PyDict::new(py);
-    py_dict
-        .set_item("type", PyString::new(py, "convexerror"))
-        .unwrap();
-    py_dict.set_item("message", err.message).unwrap();
-    py_dict.set_item("data", value_to_py(py, err.data)).unwrap();
-    py_dict.into()
+/// Raise a `ConvexError` as a Python exception.
+pub fn raise_convex_error(py: Python<'_>, err: ConvexError) -> PyErr {
+    match build_convex_error(py, err) {
+        Ok(exc) => PyErr::from_value(exc),
+        Err(e) => e,
+    }
+}
+
+/// Raise a `ConvexServerError` as a Python exception.
+pub fn raise_server_error(py: Python<'_>, message: String) -> PyErr {
+    match build_server_error(py, message) {
+        Ok(exc) => PyErr::from_value(exc),
+        Err(e) => e,
+    }
+}
+
+/// Translate a `FunctionResult` into either a decoded Python value or a
+/// raised `ConvexError`/`ConvexServerError`, shared by every call site that
+/// turns a query/mutation/action/subscription result into something Python
+/// can see.
+pub fn function_result_to_py(py: Python<'_>, result: FunctionResult) -> PyResult<PyObject> {
+    match result {
+        FunctionResult::Value(v) => value_to_py(py, v),
+        FunctionResult::ErrorMessage(e) => Err(raise_server_error(py, e)),
+        FunctionResult::ConvexError(v) => Err(raise_convex_error(py, v)),
+    }
 }
 
-pub fn value_to_py(py: Python<'_>, v: convex::Value) -> PyObject {
-    match v {
+/// Translate a Convex value to Python. Returns `Err` rather than panicking if
+/// the `_convex.int64` module can't be imported or a Python call fails, so a
+/// conversion failure raises a catchable exception instead of aborting the
+/// interpreter.
+pub fn value_to_py(py: Python<'_>, v: convex::Value) -> PyResult<PyObject> {
+    let obj = match v {
         convex::Value::Null => py.None(),
         convex::Value::Int64(val) => {
-            let int64_module = py
-                .import("_convex.int64")
-                .expect("Couldn't import _convex.int64");
-            let int_64_class = int64_module
-                .getattr("ConvexInt64")
-                .expect("Couldn't import ConvexInt64 from _convex.int64");
-            let obj: PyObject = int_64_class
-                .call((val,), None)
-                .unwrap_or_else(|_| panic!("Couldn't construct ConvexInt64() from {:?}", val))
-                .into();
-            obj
+            let int64_module = py.import("_convex.int64")?;
+            let int_64_class = int64_module.getattr("ConvexInt64")?;
+            int_64_class.call1((val,))?.into()
         },
 
         convex::Value::Float64(val) => PyFloat::new(py, val).into(),
@@ -66,25 +101,34 @@ pub fn value_to_py(py: Python<'_>, v: convex::Value) -> PyObject {
         convex::Value::Array(arr) => {
             let py_list = PyList::empty(py);
             for item in arr {
-                py_list.append(value_to_py(py, item)).unwrap();
+                py_list.append(value_to_py(py, item)?)?;
             }
             py_list.into()
         },
         convex::Value::Object(obj) => {
             let py_dict = PyDict::new(py);
             for (key, value) in obj {
-                py_dict.set_item(key, value_to_py(py, value)).unwrap();
+                py_dict.set_item(key, value_to_py(py, value)?)?;
             }
             py_dict.into()
         },
-    }
+    };
+    Ok(obj)
 }
 
-// TODO Implement all or most of the coercions from the Python client.
 /// Translate a Python value to Rust, doing isinstance coersion (e.g. subclasses
-/// of list will be interpreted as lists) but not other conversions (e.g. tuple
-/// to list).
-pub fn py_to_value(py_val: Borrowed<'_, '_, PyAny>) -> PyResult<convex::Value> {
+/// of list will be interpreted as lists) and some other conversions (e.g.
+/// tuple/set/frozenset to array) but not others (e.g. no lossy string->number
+/// coercion).
+///
+/// `strict_int_mode` is the per-client/per-call setting from
+/// `PyConvexClient`'s constructor (see `FunctionArgsWrapper::into_values`):
+/// when set, every Python `int` is coerced to `Value::Int64`, even when it
+/// would fit exactly in an `f64`. By default, only ints outside
+/// `-2^53..=2^53` (see [`MAX_SAFE_INTEGER`]) are coerced to `Value::Int64`,
+/// even those past that range that an `f64` happens to hold exactly;
+/// everything else becomes a `Value::Float64` to match plain Convex numbers.
+pub fn py_to_value(py_val: Borrowed<'_, '_, PyAny>, strict_int_mode: bool) -> PyResult<convex::Value> {
     let py = py_val.py();
     let int64_module = py.import("_convex.int64")?;
     let int_64_class = int64_module.getattr("ConvexInt64")?;
@@ -95,12 +139,25 @@ pub fn py_to_value(py_val: Borrowed<'_, '_, PyAny>) -> PyResult<convex::Value> {
         return Ok(convex::Value::Boolean(val));
     }
     if py_val.is_instance_of::<PyInt>() {
-        // Note conversion from int to float
-        let val: f64 = py_val.extract()?;
-        return Ok(convex::Value::Float64(val));
+        let val: i64 = py_val.extract().map_err(|_| {
+            PyException::new_err(format!(
+                "Integer {:?} doesn't fit in a 64-bit Convex integer",
+                py_val
+            ))
+        })?;
+        if strict_int_mode || !(-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&val) {
+            return Ok(convex::Value::Int64(val));
+        }
+        // Safe to round-trip through an f64 without losing precision.
+        return Ok(convex::Value::Float64(val as f64));
     }
     if py_val.is_instance_of::<PyFloat>() {
         let val: f64 = py_val.extract::<f64>()?;
+        if val.is_nan() || val.is_infinite() {
+            return Err(PyException::new_err(
+                "Convex doesn't support NaN or infinite float values",
+            ));
+        }
         return Ok(convex::Value::Float64(val));
     }
     if py_val.is_instance(&int_64_class)? {
@@ -116,12 +173,14 @@ pub fn py_to_value(py_val: Borrowed<'_, '_, PyAny>) -> PyResult<convex::Value> {
         let val: Vec<u8> = py_val.extract::<Vec<u8>>()?;
         return Ok(convex::Value::Bytes(val));
     }
-    if py_val.is_instance_of::<PyList>() {
-        let py_list = py_val.downcast::<PyList>()?;
+    if py_val.is_instance_of::<PyList>()
+        || py_val.is_instance_of::<PyTuple>()
+        || py_val.is_instance_of::<PySet>()
+        || py_val.is_instance_of::<PyFrozenSet>()
+    {
         let mut vec: Vec<convex::Value> = Vec::new();
-        for item in py_list {
-            let inner_value: convex::Value = py_to_value(item.as_borrowed())?;
-            vec.push(inner_value);
+        for item in py_val.try_iter()? {
+            vec.push(py_to_value(item?.as_borrowed(), strict_int_mode)?);
         }
         return Ok(convex::Value::Array(vec));
     }
@@ -129,8 +188,8 @@ pub fn py_to_value(py_val: Borrowed<'_, '_, PyAny>) -> PyResult<convex::Value> {
         let py_dict = py_val.downcast::<PyDict>()?;
         let mut map: BTreeMap<String, convex::Value> = BTreeMap::new();
         for (key, value) in py_dict.iter() {
-            let inner_value: convex::Value = py_to_value(value.as_borrowed())?;
-            let inner_key: convex::Value = py_to_value(key.as_borrowed())?;
+            let inner_value: convex::Value = py_to_value(value.as_borrowed(), strict_int_mode)?;
+            let inner_key: convex::Value = py_to_value(key.as_borrowed(), strict_int_mode)?;
             match inner_key {
                 convex::Value::String(s) => map.insert(s, inner_value),
                 _ => {
@@ -153,3 +212,118 @@ pub fn py_to_value(py_val: Borrowed<'_, '_, PyAny>) -> PyResult<convex::Value> {
         py_val.get_type()
     )))
 }
+
+#[cfg(test)]
+mod tests {
+    use pyo3::IntoPyObject;
+
+    use super::*;
+
+    fn py_int(py: Python<'_>, val: i64) -> Bound<'_, PyAny> {
+        val.into_pyobject(py).unwrap().into_any()
+    }
+
+    fn py_float(py: Python<'_>, val: f64) -> Bound<'_, PyAny> {
+        val.into_pyobject(py).unwrap().into_any()
+    }
+
+    #[test]
+    fn test_ints_stay_float64_up_to_the_safe_integer_boundary() {
+        Python::attach(|py| {
+            for safe in [MAX_SAFE_INTEGER - 1, MAX_SAFE_INTEGER, -MAX_SAFE_INTEGER] {
+                let val = py_to_value(py_int(py, safe).as_borrowed(), false).unwrap();
+                assert_eq!(val, convex::Value::Float64(safe as f64));
+            }
+        });
+    }
+
+    #[test]
+    fn test_ints_past_the_safe_integer_boundary_become_int64() {
+        Python::attach(|py| {
+            for unsafe_int in [MAX_SAFE_INTEGER + 1, -MAX_SAFE_INTEGER - 1] {
+                let val = py_to_value(py_int(py, unsafe_int).as_borrowed(), false).unwrap();
+                assert_eq!(val, convex::Value::Int64(unsafe_int));
+            }
+        });
+    }
+
+    #[test]
+    fn test_strict_int_mode_coerces_in_range_ints_to_int64_too() {
+        Python::attach(|py| {
+            let val = py_to_value(py_int(py, 1).as_borrowed(), true).unwrap();
+            assert_eq!(val, convex::Value::Int64(1));
+        });
+    }
+
+    #[test]
+    fn test_nan_and_infinite_floats_are_rejected() {
+        Python::attach(|py| {
+            for bad in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                py_to_value(py_float(py, bad).as_borrowed(), false).unwrap_err();
+            }
+        });
+    }
+
+    #[test]
+    fn test_tuples_sets_and_frozensets_coerce_to_array() {
+        Python::attach(|py| {
+            let tuple = PyTuple::new(py, [1i64]).unwrap();
+            assert_eq!(
+                py_to_value(tuple.as_any().as_borrowed(), false).unwrap(),
+                convex::Value::Array(vec![convex::Value::Float64(1.0)])
+            );
+
+            let set = PySet::new(py, [1i64]).unwrap();
+            assert_eq!(
+                py_to_value(set.as_any().as_borrowed(), false).unwrap(),
+                convex::Value::Array(vec![convex::Value::Float64(1.0)])
+            );
+
+            let frozenset = PyFrozenSet::new(py, [1i64]).unwrap();
+            assert_eq!(
+                py_to_value(frozenset.as_any().as_borrowed(), false).unwrap(),
+                convex::Value::Array(vec![convex::Value::Float64(1.0)])
+            );
+        });
+    }
+
+    #[test]
+    fn test_function_result_value_returns_the_decoded_value() {
+        Python::attach(|py| {
+            let obj = function_result_to_py(py, FunctionResult::Value(convex::Value::Float64(1.0)))
+                .unwrap();
+            let val: f64 = obj.extract(py).unwrap();
+            assert_eq!(val, 1.0);
+        });
+    }
+
+    #[test]
+    fn test_function_result_error_message_raises_convex_server_error() {
+        Python::attach(|py| {
+            let err =
+                function_result_to_py(py, FunctionResult::ErrorMessage("boom".to_string()))
+                    .unwrap_err();
+            let py_err = err.value(py);
+            assert_eq!(py_err.get_type().qualname().unwrap(), "ConvexServerError");
+            let message: String = py_err.getattr("message").unwrap().extract().unwrap();
+            assert_eq!(message, "boom");
+        });
+    }
+
+    #[test]
+    fn test_function_result_convex_error_raises_convex_error_with_message_and_data() {
+        Python::attach(|py| {
+            let rust_err = ConvexError {
+                message: "custom".to_string(),
+                data: convex::Value::String("payload".to_string()),
+            };
+            let err = function_result_to_py(py, FunctionResult::ConvexError(rust_err)).unwrap_err();
+            let py_err = err.value(py);
+            assert_eq!(py_err.get_type().qualname().unwrap(), "ConvexError");
+            let message: String = py_err.getattr("message").unwrap().extract().unwrap();
+            assert_eq!(message, "custom");
+            let data: String = py_err.getattr("data").unwrap().extract().unwrap();
+            assert_eq!(data, "payload");
+        });
+    }
+}
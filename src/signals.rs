@@ -0,0 +1,98 @@
+//! Helpers for making blocking Rust<->Python calls responsive to Ctrl-C.
+//!
+//! Blocking calls that drive a tokio future from a Python thread (e.g.
+//! `PyConvexClient::query` or `PyQuerySubscription::next`) need to notice a
+//! `KeyboardInterrupt` without waiting for the underlying network call to
+//! finish. [`check_python_signals_periodically`] polls for a pending signal
+//! at an interval -- [`default_poll_interval`] (itself overridable via the
+//! `CONVEX_SIGNAL_POLL_INTERVAL_MS` env var) unless a `PyConvexClient` was
+//! constructed with its own `poll_interval_ms` override -- and
+//! [`run_cancellable`] races it against a future that owns some shared
+//! state, making sure that state is put back once the future finishes even
+//! if Python cancelled the wait first.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use pyo3::{
+    exceptions::PyException,
+    PyErr,
+    PyResult,
+    Python,
+};
+use tokio::{
+    runtime::Runtime,
+    time::sleep,
+};
+
+const DEFAULT_POLL_INTERVAL_MS: u64 = 50;
+const POLL_INTERVAL_ENV_VAR: &str = "CONVEX_SIGNAL_POLL_INTERVAL_MS";
+
+/// The default poll interval used by a `PyConvexClient` that wasn't
+/// constructed with its own `poll_interval_ms` override. Configurable
+/// process-wide via the `CONVEX_SIGNAL_POLL_INTERVAL_MS` environment
+/// variable (milliseconds); defaults to 50ms.
+pub fn default_poll_interval() -> Duration {
+    std::env::var(POLL_INTERVAL_ENV_VAR)
+        .ok()
+        .and_then(|ms| ms.parse::<u64>().ok())
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(DEFAULT_POLL_INTERVAL_MS))
+}
+
+/// Resolves once a Python signal handler raises an exception (e.g. the user
+/// hit Ctrl-C), polling every `interval`.
+pub async fn check_python_signals_periodically(interval: Duration) -> PyErr {
+    loop {
+        sleep(interval).await;
+        if let Err(e) = Python::attach(|py| py.check_signals()) {
+            return e;
+        }
+    }
+}
+
+/// Take `slot`'s contents, drive them through `next_fn` on `rt`, and race
+/// the result against [`check_python_signals_periodically`].
+///
+/// Unlike a plain `tokio::select!` over the future directly, the future is
+/// spawned onto `rt` rather than polled in place, so if a signal wins the
+/// race the spawned task keeps running in the background instead of being
+/// dropped. Once it finishes, it puts its state back into `slot` itself, so
+/// the stream is usable again rather than being left in a "requires reset"
+/// state. Until that task finishes, `slot` stays empty and a further call
+/// returns `missing_err`; the result the task produced after the signal won
+/// is discarded.
+pub fn run_cancellable<S, T, Fut, FutFn>(
+    rt: &Runtime,
+    poll_interval: Duration,
+    slot: &Arc<Mutex<Option<S>>>,
+    missing_err: PyErr,
+    next_fn: FutFn,
+) -> PyResult<T>
+where
+    S: Send + 'static,
+    T: Send + 'static,
+    Fut: Future<Output = (S, T)> + Send + 'static,
+    FutFn: FnOnce(S) -> Fut + Send + 'static,
+{
+    let Some(inner) = slot.lock().take() else {
+        return Err(missing_err);
+    };
+    let slot = slot.clone();
+    let handle = rt.spawn(async move {
+        let (inner, result) = next_fn(inner).await;
+        slot.lock().replace(inner);
+        result
+    });
+    rt.block_on(async {
+        tokio::select!(
+            res = handle => res.map_err(|e| PyException::new_err(e.to_string())),
+            err = check_python_signals_periodically(poll_interval) => Err(err),
+        )
+    })
+}
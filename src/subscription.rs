@@ -5,6 +5,7 @@ use std::{
         Hasher,
     },
     sync::Arc,
+    time::Duration,
 };
 
 use convex::{
@@ -15,7 +16,6 @@ use futures::StreamExt;
 use parking_lot::Mutex;
 use pyo3::{
     exceptions::{
-        PyException,
         PyNotImplementedError,
         PyStopAsyncIteration,
         PyStopIteration,
@@ -24,29 +24,39 @@ use pyo3::{
     pyclass::CompareOp,
     types::PyDict,
 };
-use tokio::time::{
-    sleep,
-    Duration,
-};
 
-use crate::query_result::{
-    convex_error_to_py_wrapped,
-    value_to_py,
-    value_to_py_wrapped,
+use crate::{
+    lazy_value::LazyValue,
+    query_result::{
+        build_convex_error,
+        build_server_error,
+        function_result_to_py,
+        value_to_py,
+    },
+    signals::run_cancellable,
 };
 
 #[pyclass(frozen)]
 pub struct PyQuerySubscription {
-    // TODO document here why this needs to be an Arc<Mutex<Option<Sub>>>
+    // An `Option` so `next()` can temporarily take ownership while it awaits,
+    // and a `Mutex` so it can still be reinserted from a task spawned onto
+    // the runtime after Python has stopped waiting on it (see
+    // `signals::run_cancellable`).
     inner: Arc<Mutex<Option<convex::QuerySubscription>>>,
-    pub rt_handle: tokio::runtime::Handle,
+    pub rt: Arc<tokio::runtime::Runtime>,
+    pub poll_interval: Duration,
 }
 
 impl PyQuerySubscription {
-    pub fn new(query_sub: convex::QuerySubscription, rt_handle: tokio::runtime::Handle) -> Self {
+    pub fn new(
+        query_sub: convex::QuerySubscription,
+        rt: Arc<tokio::runtime::Runtime>,
+        poll_interval: Duration,
+    ) -> Self {
         PyQuerySubscription {
             inner: Arc::new(Mutex::new(Some(query_sub))),
-            rt_handle,
+            rt,
+            poll_interval,
         }
     }
 }
@@ -89,15 +99,6 @@ impl PySubscriberId {
     }
 }
 
-async fn check_python_signals_periodically() -> PyErr {
-    loop {
-        sleep(Duration::from_secs(1)).await;
-        if let Err(e) = Python::with_gil(|py| py.check_signals()) {
-            return e;
-        }
-    }
-}
-
 #[pymethods]
 impl PyQuerySubscription {
     fn exists(&self) -> bool {
@@ -105,12 +106,13 @@ impl PyQuerySubscription {
     }
 
     #[getter]
-    fn id(&self) -> PySubscriberId {
-        let query_sub = self.inner.clone();
-        let query_sub_inner = query_sub.lock().take().unwrap();
+    fn id(&self) -> PyResult<PySubscriberId> {
+        let Some(query_sub_inner) = self.inner.lock().take() else {
+            return Err(PyStopIteration::new_err("Stream requires reset"));
+        };
         let sub_id: SubscriberId = *query_sub_inner.id();
-        let _ = query_sub.lock().insert(query_sub_inner);
-        PySubscriberId::from(sub_id)
+        let _ = self.inner.lock().insert(query_sub_inner);
+        Ok(PySubscriberId::from(sub_id))
     }
 
     // Drops the inner subscription object, which causes a
@@ -120,31 +122,19 @@ impl PyQuerySubscription {
     }
 
     fn next(&self, py: Python) -> PyResult<PyObject> {
-        let query_sub = self.inner.clone();
-        let res = self.rt_handle.block_on(async {
-            tokio::select!(
-                res1 = async move {
-                    let query_sub_inner = query_sub.lock().take();
-                    if query_sub_inner.is_none() {
-                        return Err(PyStopIteration::new_err("Stream requires reset"));
-                    }
-                    let mut query_sub_inner = query_sub_inner.unwrap();
-                    let res = query_sub_inner.next().await;
-                    let _ = query_sub.lock().insert(query_sub_inner);
-                    Ok(res)
-                } => res1,
-                res2 = check_python_signals_periodically() => Err(res2)
-            )
-        })?;
-        match res.unwrap() {
-            FunctionResult::Value(v) => Ok(value_to_py_wrapped(py, v)),
-            FunctionResult::ErrorMessage(e) => Err(PyException::new_err(e)),
-            FunctionResult::ConvexError(v) => {
-                // pyo3 can't defined new custom exceptions when using the common abi
-                // `features = ["abi3"]` https://github.com/PyO3/pyo3/issues/1344
-                // so we define this error in Python. So just return a wrapped one.
-                Ok(convex_error_to_py_wrapped(py, v))
+        let res = run_cancellable(
+            &self.rt,
+            self.poll_interval,
+            &self.inner,
+            PyStopIteration::new_err("Stream requires reset"),
+            |mut query_sub_inner| async move {
+                let res = query_sub_inner.next().await;
+                (query_sub_inner, res)
             },
+        )?;
+        match res {
+            Some(result) => function_result_to_py(py, result),
+            None => Err(PyStopIteration::new_err("Subscription stream ended")),
         }
     }
 
@@ -158,32 +148,48 @@ impl PyQuerySubscription {
             let mut query_sub_inner = query_sub_inner.unwrap();
             let res = query_sub_inner.next().await;
             let _ = query_sub.lock().insert(query_sub_inner);
-            Python::with_gil(|py| match res.unwrap() {
-                FunctionResult::Value(v) => Ok(value_to_py_wrapped(py, v)),
-                FunctionResult::ErrorMessage(e) => Err(PyException::new_err(e)),
-                FunctionResult::ConvexError(v) => {
-                    // pyo3 can't defined new custom exceptions when using the common abi
-                    // `features = ["abi3"]` https://github.com/PyO3/pyo3/issues/1344
-                    // so we define this error in Python. So just return a wrapped one.
-                    Ok(convex_error_to_py_wrapped(py, v))
-                },
+            Python::with_gil(|py| match res {
+                Some(result) => function_result_to_py(py, result),
+                None => Err(PyStopAsyncIteration::new_err("Subscription stream ended")),
             })
         })?;
         Ok(fut.unbind())
     }
+
+    fn __iter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.next(py)
+    }
+
+    fn __aiter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __anext__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.anext(py)
+    }
 }
 
 #[pyclass(frozen)]
 pub struct PyQuerySetSubscription {
     inner: Arc<Mutex<Option<convex::QuerySetSubscription>>>,
-    pub rt_handle: Option<tokio::runtime::Handle>,
+    pub rt: Option<Arc<tokio::runtime::Runtime>>,
+    pub poll_interval: Duration,
+    // When set, values are returned as `LazyValue` views instead of being
+    // fully decoded into Python dicts/lists on every update.
+    pub lazy: bool,
 }
 
 impl From<convex::QuerySetSubscription> for PyQuerySetSubscription {
     fn from(query_set_sub: convex::QuerySetSubscription) -> Self {
         PyQuerySetSubscription {
             inner: Arc::new(Mutex::new(Some(query_set_sub))),
-            rt_handle: None,
+            rt: None,
+            poll_interval: crate::signals::default_poll_interval(),
+            lazy: false,
         }
     }
 }
@@ -195,23 +201,20 @@ impl PyQuerySetSubscription {
     }
 
     fn next(&self, py: Python) -> PyResult<PyObject> {
-        let query_sub = self.inner.clone();
-        let res = self.rt_handle.as_ref().unwrap().block_on(async {
-            tokio::select!(
-                res1 = async move {
-                    let query_sub_inner = query_sub.lock().take();
-                    if query_sub_inner.is_none() {
-                        return Err(PyStopIteration::new_err("Stream requires reset"));
-                    }
-                    let mut query_sub_inner = query_sub_inner.unwrap();
-                    let res = query_sub_inner.next().await;
-                    let _ = query_sub.lock().insert(query_sub_inner);
-                    Ok(res)
-                } => res1,
-                res2 = check_python_signals_periodically() => Err(res2)
-            )
-        })?;
-        let query_results = res.unwrap();
+        let rt = self.rt.as_ref().unwrap();
+        let res = run_cancellable(
+            rt,
+            self.poll_interval,
+            &self.inner,
+            PyStopIteration::new_err("Stream requires reset"),
+            |mut query_sub_inner| async move {
+                let res = query_sub_inner.next().await;
+                (query_sub_inner, res)
+            },
+        )?;
+        let Some(query_results) = res else {
+            return Err(PyStopIteration::new_err("Subscription stream ended"));
+        };
         let py_dict = PyDict::new(py);
         for (sub_id, function_result) in query_results.iter() {
             if function_result.is_none() {
@@ -219,30 +222,23 @@ impl PyQuerySetSubscription {
             }
             let py_sub_id: PySubscriberId = (*sub_id).into();
 
+            // Each subscription in the set can fail independently, so rather than
+            // raising we surface a per-subscription-id exception instance as the
+            // value; callers that want query-like behavior can `raise` it themselves.
             let sub_value: PyObject = match function_result.unwrap() {
-                FunctionResult::Value(v) => value_to_py_wrapped(py, v.clone()),
-                FunctionResult::ErrorMessage(e) => {
-                    // TODO this is wrong!
-                    value_to_py(py, convex::Value::String(e.clone()))
-                },
-                FunctionResult::ConvexError(v) => {
-                    // pyo3 can't defined new custom exceptions when using the common abi
-                    // `features = ["abi3"]` https://github.com/PyO3/pyo3/issues/1344
-                    // so we define this error in Python. So just return a wrapped one.
-                    convex_error_to_py_wrapped(py, v.clone())
-                        .into_pyobject(py)?
-                        .unbind()
-                },
+                FunctionResult::Value(v) if self.lazy => LazyValue::wrap(py, v.clone())?,
+                FunctionResult::Value(v) => value_to_py(py, v.clone())?,
+                FunctionResult::ErrorMessage(e) => build_server_error(py, e.clone())?.unbind(),
+                FunctionResult::ConvexError(v) => build_convex_error(py, v.clone())?.unbind(),
             };
-            py_dict
-                .set_item(py_sub_id.into_pyobject(py)?, sub_value)
-                .unwrap();
+            py_dict.set_item(py_sub_id.into_pyobject(py)?, sub_value)?;
         }
         Ok(py_dict.into_any().unbind())
     }
 
     fn anext(&self, py: Python<'_>) -> PyResult<PyObject> {
         let query_sub = self.inner.clone();
+        let lazy = self.lazy;
         let fut = pyo3_async_runtimes::tokio::future_into_py(py, async move {
             let query_sub_inner = query_sub.lock().take();
             if query_sub_inner.is_none() {
@@ -253,7 +249,9 @@ impl PyQuerySetSubscription {
             let _ = query_sub.lock().insert(query_sub_inner);
 
             Python::with_gil(|py| -> PyResult<PyObject> {
-                let query_results = res.unwrap();
+                let Some(query_results) = res else {
+                    return Err(PyStopAsyncIteration::new_err("Subscription stream ended"));
+                };
                 let py_dict = PyDict::new(py);
                 for (sub_id, function_result) in query_results.iter() {
                     if function_result.is_none() {
@@ -261,27 +259,34 @@ impl PyQuerySetSubscription {
                     }
                     let py_sub_id: PySubscriberId = (*sub_id).into();
                     let sub_value: PyObject = match function_result.unwrap() {
-                        FunctionResult::Value(v) => value_to_py(py, v.clone()),
-                        // TODO: this conflates errors with genuine values
+                        FunctionResult::Value(v) if lazy => LazyValue::wrap(py, v.clone())?,
+                        FunctionResult::Value(v) => value_to_py(py, v.clone())?,
                         FunctionResult::ErrorMessage(e) => {
-                            value_to_py(py, convex::Value::String(e.to_string()))
-                        },
-                        FunctionResult::ConvexError(e) => {
-                            let e = e.clone();
-                            (
-                                value_to_py(py, convex::Value::String(e.message)),
-                                value_to_py(py, e.data),
-                            )
-                                .into_pyobject(py)?
-                                .into_any()
-                                .unbind()
+                            build_server_error(py, e.clone())?.unbind()
                         },
+                        FunctionResult::ConvexError(e) => build_convex_error(py, e.clone())?.unbind(),
                     };
-                    py_dict.set_item(py_sub_id, sub_value).unwrap();
+                    py_dict.set_item(py_sub_id, sub_value)?;
                 }
                 Ok(py_dict.into())
             })
         })?;
         Ok(fut.unbind())
     }
+
+    fn __iter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.next(py)
+    }
+
+    fn __aiter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __anext__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.anext(py)
+    }
 }